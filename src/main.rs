@@ -5,12 +5,17 @@ use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use bevy_pixel_camera::{PixelCameraPlugin, PixelViewport, PixelZoom};
 
 mod map;
+mod map_generator;
+mod pathfinding;
 mod player;
+mod spatial;
+mod tiled_loader;
 
 mod prelude {
     pub use bevy::prelude::*;
     pub use bevy_ecs_tilemap::prelude::*;
 
+    pub use super::FovSource;
     pub use super::GridPosition;
     pub use super::LoadingAssets;
     pub use super::MainStates;
@@ -27,6 +32,11 @@ pub enum MainStates {
 #[derive(Component, Deref, DerefMut, Default, Debug)]
 pub struct GridPosition(TilePos);
 
+/// Marker for the entity whose `GridPosition` drives the map's
+/// field-of-view computation, e.g. the player.
+#[derive(Component, Default)]
+pub struct FovSource;
+
 /// Component for signaling that an entity with a GridPosition
 /// should move to the specified grid position.
 #[derive(Component)]
@@ -122,23 +132,71 @@ fn detect_assets_loaded(
     }
 }
 
+/// Matches the `PixelZoom::Fixed` factor set up on the main camera, so
+/// the visible world-space area can be derived from the window size.
+const PIXEL_ZOOM: f32 = 4.0;
+
+/// How quickly the camera eases toward the clamped follow target, in
+/// units of "fraction of the remaining distance per second".
+const CAMERA_FOLLOW_SPEED: f32 = 10.0;
+
+/// Clamps `target` so the camera never shows space beyond the map's
+/// world-space rectangle, centering on an axis instead if the map is
+/// narrower than the visible viewport along it.
+fn clamp_to_map_bounds(target: Vec2, map_settings: &map::MapSettings, window: &Window) -> Vec2 {
+    let map_size = Vec2::new(
+        map_settings.size.0 as f32 * map_settings.tile_size,
+        map_settings.size.1 as f32 * map_settings.tile_size,
+    );
+    // Tiles are centred on `pos * tile_size`, so the map's edges lie half
+    // a tile beyond the first and last tile centres.
+    let min = Vec2::splat(-map_settings.tile_size / 2.0);
+    let max = min + map_size;
+    let viewport = Vec2::new(window.width(), window.height()) / PIXEL_ZOOM;
+    let half_viewport = viewport / 2.0;
+
+    Vec2::new(
+        if map_size.x < viewport.x {
+            (min.x + max.x) / 2.0
+        } else {
+            target.x.clamp(min.x + half_viewport.x, max.x - half_viewport.x)
+        },
+        if map_size.y < viewport.y {
+            (min.y + max.y) / 2.0
+        } else {
+            target.y.clamp(min.y + half_viewport.y, max.y - half_viewport.y)
+        },
+    )
+}
+
 fn camera_follow_player(
     mut camera_query: Query<&mut Transform, With<MainCamera>>,
     player_query: Query<&Transform, (With<player::Player>, Without<MainCamera>)>,
+    map_settings: Res<map::MapSettings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    time: Res<Time>,
 ) {
-    if let Ok(player_transform) = player_query.get_single() {
-        match camera_query.get_single_mut() {
-            Ok(mut camera_transform) => {
-                camera_transform.translation = player_transform
-                    .translation
-                    .xy()
-                    .extend(camera_transform.translation.z);
-            }
-            Err(QuerySingleError::MultipleEntities(_)) => {
-                panic!("There is more than one MainCamera, this should not happen!")
-            }
-            _ => {}
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+
+    match camera_query.get_single_mut() {
+        Ok(mut camera_transform) => {
+            let target = clamp_to_map_bounds(player_transform.translation.xy(), &map_settings, window);
+            let current = camera_transform.translation.xy();
+            let smoothing = (CAMERA_FOLLOW_SPEED * time.delta_seconds()).min(1.0);
+
+            camera_transform.translation = current
+                .lerp(target, smoothing)
+                .extend(camera_transform.translation.z);
+        }
+        Err(QuerySingleError::MultipleEntities(_)) => {
+            panic!("There is more than one MainCamera, this should not happen!")
         }
+        _ => {}
     }
 }
 
@@ -149,6 +207,7 @@ fn main() {
         .add_plugins(player::PlayerPlugin)
         .add_plugins(PixelCameraPlugin)
         .add_plugins(map::MapPlugin)
+        .add_plugins(spatial::SpatialPlugin)
         .add_state::<MainStates>()
         .insert_resource(LoadingAssets { assets: Vec::new() })
         .insert_resource(Msaa::Off)
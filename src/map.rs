@@ -1,10 +1,15 @@
 use bevy::asset::LoadState;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy_common_assets::ron::RonAssetPlugin;
 use bevy_ecs_tilemap::helpers::square_grid::neighbors::{Neighbors, SquareDirection};
 use bevy_ecs_tilemap::prelude::*;
 use std::collections::HashMap;
+use std::sync::{mpsc, Mutex};
 
+use crate::map_generator::{seed_from_str, CellClass, MapGenerator};
 use crate::prelude::*;
+use crate::spatial::SpatialIndex;
+use crate::tiled_loader::{TiledMap, TiledMapLoader};
 
 pub struct MapPlugin;
 
@@ -21,6 +26,12 @@ pub struct TileType(String);
 #[derive(Component, Debug, Clone, Deref, DerefMut)]
 pub struct TileDigging(Timer);
 
+/// Whether a tile has ever been inside the player's field of view.
+/// Explored tiles stay dimly visible once they leave FOV, rather than
+/// fading back into the fog.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct TileExplored(bool);
+
 impl TileDigging {
     pub fn new(time: f32) -> Self {
         TileDigging(Timer::from_seconds(time, TimerMode::Once))
@@ -81,6 +92,7 @@ pub struct GameTileBundle {
     tile_bundle: TileBundle,
     tile_texture: TileTexture,
     tile_terrain: TileTerrain,
+    tile_explored: TileExplored,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -123,6 +135,7 @@ impl TileInfo {
             },
             tile_texture: data.tile_texture.clone(),
             tile_terrain: data.tile_type.clone(),
+            tile_explored: TileExplored::default(),
         })
     }
 }
@@ -135,16 +148,40 @@ enum MapStates {
     Generated,
 }
 
+/// Where a map's tile layout comes from.
+#[derive(Debug, Clone)]
+pub enum MapSource {
+    /// `MapGenerator`-driven cellular-automata caves.
+    Procedural { seed: u64 },
+    /// An externally authored Tiled map, loaded from the asset folder.
+    Tiled { path: String },
+}
+
+impl Default for MapSource {
+    fn default() -> Self {
+        MapSource::Procedural { seed: 0 }
+    }
+}
+
 #[derive(Debug, Clone, Default, Resource)]
 pub struct MapSettings {
+    /// For `MapSource::Tiled`, overwritten with the loaded map's
+    /// dimensions once it's ready.
     pub size: (u32, u32),
     pub tile_size: f32,
+    pub source: MapSource,
 }
 
+/// Where the player should spawn; the geometric center for procedural
+/// maps, or a named spawn object for Tiled maps.
+#[derive(Debug, Clone, Copy, Default, Resource)]
+pub struct MapSpawnPoint(pub TilePos);
+
 #[derive(Resource)]
 pub struct MapAssets {
     pub texture: Handle<Image>,
     pub tile_info: Handle<TileInfo>,
+    pub tiled_map: Option<Handle<TiledMap>>,
 }
 
 fn update_tile(
@@ -232,10 +269,16 @@ fn update_tile_digging(
     }
 }
 
-fn load_map_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn load_map_assets(mut commands: Commands, asset_server: Res<AssetServer>, settings: Res<MapSettings>) {
+    let tiled_map = match &settings.source {
+        MapSource::Tiled { path } => Some(asset_server.load(path.clone())),
+        MapSource::Procedural { .. } => None,
+    };
+
     commands.insert_resource(MapAssets {
         texture: asset_server.load("tilemap.png"),
         tile_info: asset_server.load("tiles.info.ron"),
+        tiled_map,
     });
 }
 
@@ -266,11 +309,17 @@ fn check_map_asset_loading(
     mut next_state: ResMut<NextState<MapStates>>,
     map_assets: Res<MapAssets>,
     tile_info_assets: Res<Assets<TileInfo>>,
+    tiled_map_assets: Res<Assets<TiledMap>>,
+    mut settings: ResMut<MapSettings>,
+    mut spawn_point: ResMut<MapSpawnPoint>,
 ) {
-    let assets = vec![
+    let mut assets = vec![
         map_assets.texture.clone().untyped(),
         map_assets.tile_info.clone().untyped(),
     ];
+    if let Some(tiled_map) = &map_assets.tiled_map {
+        assets.push(tiled_map.clone().untyped());
+    }
 
     match get_group_load_state(asset_server, assets) {
         LoadState::Loaded => {
@@ -280,71 +329,410 @@ fn check_map_asset_loading(
                 .expect("TileInfo should be loaded!");
 
             commands.insert_resource(tile_info.clone());
+
+            if let Some(tiled_map) = map_assets
+                .tiled_map
+                .as_ref()
+                .and_then(|handle| tiled_map_assets.get(handle))
+            {
+                settings.size = (tiled_map.width, tiled_map.height);
+                spawn_point.0 = tiled_map.spawn;
+            }
         }
         _ => {}
     }
 }
 
+/// Field-of-view radius, in tiles, around an `FovSource` entity.
+const FOV_RADIUS: i32 = 8;
+
+/// Color tint applied to tiles that have been seen before but are not
+/// currently in the player's field of view.
+fn explored_tint() -> Color {
+    Color::rgba(0.35, 0.35, 0.45, 1.0)
+}
+
+/// Octant transform multipliers `(xx, xy, yx, yy)` mapping octant-local
+/// (row, col) coordinates onto world-space offsets.
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+/// Recursive shadowcasting over a single octant, starting at `row` tiles
+/// out from the origin with the given slope bounds. `is_opaque` and
+/// `mark_visible` are given world-space `(x, y)` coordinates.
+fn cast_light(
+    row: i32,
+    mut start_slope: f32,
+    end_slope: f32,
+    radius: i32,
+    (xx, xy, yx, yy): (i32, i32, i32, i32),
+    is_opaque: &dyn Fn(i32, i32) -> bool,
+    mark_visible: &mut dyn FnMut(i32, i32),
+) {
+    if start_slope < end_slope {
+        return;
+    }
+
+    let mut blocked = false;
+    let mut next_start_slope = start_slope;
+
+    for row in row..=radius {
+        // The radial coordinate must stay negative for the slope math
+        // below to keep its sign: `dy = -row`, not `row` itself.
+        let dy = -row;
+        let mut col = -row;
+        while col <= 0 {
+            let left_slope = (col as f32 - 0.5) / (dy as f32 + 0.5);
+            let right_slope = (col as f32 + 0.5) / (dy as f32 - 0.5);
+
+            if start_slope < right_slope {
+                col += 1;
+                continue;
+            }
+            if end_slope > left_slope {
+                break;
+            }
+
+            let world_x = col * xx + dy * xy;
+            let world_y = col * yx + dy * yy;
+
+            if col * col + dy * dy <= radius * radius {
+                mark_visible(world_x, world_y);
+            }
+
+            if blocked {
+                if is_opaque(world_x, world_y) {
+                    next_start_slope = right_slope;
+                    col += 1;
+                    continue;
+                }
+                blocked = false;
+                start_slope = next_start_slope;
+            } else if is_opaque(world_x, world_y) && row < radius {
+                blocked = true;
+                next_start_slope = right_slope;
+                cast_light(
+                    row + 1,
+                    start_slope,
+                    left_slope,
+                    radius,
+                    (xx, xy, yx, yy),
+                    is_opaque,
+                    mark_visible,
+                );
+            }
+
+            col += 1;
+        }
+
+        if blocked {
+            break;
+        }
+    }
+}
+
+/// Computes visible tiles around `origin` out to `radius`, always
+/// including the origin itself, across all 8 octants.
+fn compute_fov(
+    radius: i32,
+    is_opaque: &dyn Fn(i32, i32) -> bool,
+    mark_visible: &mut dyn FnMut(i32, i32),
+) {
+    mark_visible(0, 0);
+    for octant in OCTANTS {
+        cast_light(1, 1.0, 0.0, radius, octant, is_opaque, mark_visible);
+    }
+}
+
+#[cfg(test)]
+mod fov_tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn lights_a_full_radius_disk_on_an_open_map() {
+        let radius = 8;
+        let mut visible = HashSet::new();
+        compute_fov(radius, &|_, _| false, &mut |x, y| {
+            visible.insert((x, y));
+        });
+
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy <= radius * radius {
+                    assert!(
+                        visible.contains(&(dx, dy)),
+                        "expected ({dx}, {dy}) within radius {radius} to be visible"
+                    );
+                }
+            }
+        }
+
+        assert!(
+            !visible.contains(&(radius + 1, 0)),
+            "tiles beyond the radius should not be lit"
+        );
+    }
+}
+
 fn update_visibility(
-    changed_query: Query<(Entity, &TilePos, &TileTerrain), Changed<TileTerrain>>,
-    mut tile_query: Query<&mut TileVisible>,
-    tile_storage: Query<(&TileStorage, &TilemapSize)>,
+    fov_source_query: Query<&GridPosition, (With<FovSource>, Changed<GridPosition>)>,
+    tile_storage_query: Query<(&TileStorage, &TilemapSize)>,
+    terrain_query: Query<&TileTerrain>,
+    mut vis_query: Query<(&mut TileVisible, &mut TileColor, &mut TileExplored)>,
 ) {
-    let (tile_storage, map_size) = tile_storage.single();
+    let Ok(origin) = fov_source_query.get_single() else {
+        return;
+    };
+    let Ok((tile_storage, map_size)) = tile_storage_query.get_single() else {
+        return;
+    };
+
+    // Drop every tile back to its "explored but not currently visible"
+    // (or fully unseen) state before re-casting light from the source.
+    for x in 0..map_size.x {
+        for y in 0..map_size.y {
+            let Some(entity) = tile_storage.get(&TilePos { x, y }) else {
+                continue;
+            };
+            let Ok((mut visible, mut color, explored)) = vis_query.get_mut(entity) else {
+                continue;
+            };
+
+            visible.0 = explored.0;
+            if explored.0 {
+                color.0 = explored_tint();
+            }
+        }
+    }
 
-    for (e, tile_pos, tile_type) in changed_query.iter() {
-        let visible = tile_query.get(e).unwrap().clone();
-        if visible.0 && *tile_type == TileTerrain::Walkable {
-            let neighbors = Neighbors::get_square_neighboring_positions(tile_pos, map_size, true);
+    let in_bounds =
+        |x: i32, y: i32| x >= 0 && y >= 0 && (x as u32) < map_size.x && (y as u32) < map_size.y;
 
-            for neighbor in neighbors.iter() {
-                let tile_entity = tile_storage.get(neighbor).unwrap();
-                let mut tile_vis = tile_query.get_mut(tile_entity).unwrap();
-                *tile_vis = visible.clone();
+    let is_opaque = |dx: i32, dy: i32| {
+        let (x, y) = (origin.x as i32 + dx, origin.y as i32 + dy);
+        if !in_bounds(x, y) {
+            return true;
+        }
+        match tile_storage
+            .get(&TilePos { x: x as u32, y: y as u32 })
+            .and_then(|e| terrain_query.get(e).ok())
+        {
+            Some(TileTerrain::Walkable) => false,
+            Some(TileTerrain::Diggable { .. }) | Some(TileTerrain::Impassable) | None => true,
+        }
+    };
+
+    let mut mark_visible = |dx: i32, dy: i32| {
+        let (x, y) = (origin.x as i32 + dx, origin.y as i32 + dy);
+        if !in_bounds(x, y) {
+            return;
+        }
+        let Some(entity) = tile_storage.get(&TilePos { x: x as u32, y: y as u32 }) else {
+            return;
+        };
+        let Ok((mut visible, mut color, mut explored)) = vis_query.get_mut(entity) else {
+            return;
+        };
+
+        visible.0 = true;
+        color.0 = Color::WHITE;
+        explored.0 = true;
+    };
+
+    compute_fov(FOV_RADIUS, &is_opaque, &mut mark_visible);
+}
+
+/// Maximum number of tiles `tile_loaders` will spawn in a single `Update`,
+/// so a big map streams in over several frames instead of stalling one.
+const MAX_TILES_TO_LOAD_IN_ONE_UPDATE: usize = 5000;
+
+/// A tile waiting to be spawned, as produced off the main thread by the
+/// map generation task. Plain data only, so it can cross the channel.
+struct TileDescriptor {
+    position: TilePos,
+    tile_type: String,
+    /// Overrides the hardness baked into the named `Diggable` tile type,
+    /// so wall tiles can get harder the further they are from spawn.
+    hardness_override: Option<f32>,
+}
+
+/// Tracks an in-progress background map generation: the tilemap entity
+/// and storage being filled in, and the channel tiles stream in on.
+///
+/// `std::sync::mpsc::Receiver` is `Send` but not `Sync`, so it can't be
+/// a bevy resource on its own; wrapping it in a `Mutex` satisfies both.
+#[derive(Resource)]
+struct MapGeneration {
+    tilemap_id: Entity,
+    storage: TileStorage,
+    receiver: Mutex<mpsc::Receiver<TileDescriptor>>,
+    _task: Task<()>,
+}
+
+/// Generates a cave layout in the background and streams its tiles
+/// through `sender`, for `MapSource::Procedural`.
+fn generate_procedural_tiles(size: (u32, u32), seed: u64, sender: mpsc::Sender<TileDescriptor>) {
+    let cells = MapGenerator::new(seed).generate(size.0, size.1);
+    let center = (size.0 as f32 / 2.0, size.1 as f32 / 2.0);
+    let max_dist = center.0.hypot(center.1).max(1.0);
+
+    for x in 0..size.0 {
+        for y in 0..size.1 {
+            let (tile_type, hardness_override) = match cells[x as usize][y as usize] {
+                CellClass::Floor => ("ground", None),
+                CellClass::Wall => {
+                    let dist = (x as f32 - center.0).hypot(y as f32 - center.1);
+                    ("ice", Some((dist / max_dist).clamp(0.1, 1.0)))
+                }
+                // Requires an Impassable "bedrock" entry in the
+                // assets/tiles.info.ron palette (not part of this
+                // source tree to confirm/add here). `tile_loaders` skips
+                // an unmapped name, which leaves the border cells empty
+                // in `TileStorage`; `update_tile` then panics when it
+                // looks one of them up as a neighbour.
+                CellClass::Border => ("bedrock", None),
+            };
+
+            let descriptor = TileDescriptor {
+                position: TilePos { x, y },
+                tile_type: tile_type.to_owned(),
+                hardness_override,
+            };
+            if sender.send(descriptor).is_err() {
+                // Receiver was dropped, no point generating further.
+                return;
             }
         }
     }
 }
 
-fn create_map(
+/// Streams an already-parsed Tiled map's tiles through `sender`, for
+/// `MapSource::Tiled`.
+fn stream_tiled_tiles(tiled_map: TiledMap, sender: mpsc::Sender<TileDescriptor>) {
+    for (position, tile_type) in tiled_map.tiles {
+        let descriptor = TileDescriptor {
+            position,
+            tile_type,
+            hardness_override: None,
+        };
+        if sender.send(descriptor).is_err() {
+            return;
+        }
+    }
+}
+
+fn start_map_generation(
     mut commands: Commands,
     settings: Res<MapSettings>,
     map_assets: Res<MapAssets>,
-    tile_info: Res<TileInfo>,
-    mut next_map_state: ResMut<NextState<MapStates>>,
-    mut next_main_state: ResMut<NextState<MainStates>>,
+    tiled_map_assets: Res<Assets<TiledMap>>,
 ) {
     println!("Creating map");
 
-    println!("{:?}", tile_info);
-
     let map_size = TilemapSize {
         x: settings.size.0,
         y: settings.size.1,
     };
     let tilemap_id = commands.spawn_empty().id();
-    let mut tile_storage = TileStorage::empty(map_size);
+    let storage = TileStorage::empty(map_size);
 
-    for x in 0..map_size.x {
-        for y in 0..map_size.y {
-            let tile_pos = TilePos { x, y };
-            let tile_bundle = tile_info
-                .create_bundle("ice", tile_pos, TilemapId(tilemap_id), false)
-                .unwrap();
-            let tile_entity = commands.spawn(tile_bundle).id();
-            tile_storage.set(&tile_pos, tile_entity)
+    let (sender, receiver) = mpsc::channel();
+
+    let task = match settings.source.clone() {
+        MapSource::Procedural { seed } => {
+            let size = settings.size;
+            AsyncComputeTaskPool::get().spawn(async move {
+                generate_procedural_tiles(size, seed, sender);
+            })
         }
-    }
+        MapSource::Tiled { .. } => {
+            let tiled_map = map_assets
+                .tiled_map
+                .as_ref()
+                .and_then(|handle| tiled_map_assets.get(handle))
+                .expect("Tiled map should be loaded before map generation starts")
+                .clone();
+            AsyncComputeTaskPool::get().spawn(async move {
+                stream_tiled_tiles(tiled_map, sender);
+            })
+        }
+    };
 
-    for x in (settings.size.0 / 2 - 1)..(settings.size.0 / 2 + 2) {
-        for y in (settings.size.1 / 2 - 1)..(settings.size.1 / 2 + 2) {
-            let tile_pos = TilePos { x, y };
-            let tile_bundle = tile_info
-                .create_bundle("ground", tile_pos, TilemapId(tilemap_id), true)
-                .unwrap();
-            let tile_entity = commands.spawn(tile_bundle).id();
-            tile_storage.set(&tile_pos, tile_entity)
+    commands.insert_resource(MapGeneration {
+        tilemap_id,
+        storage,
+        receiver: Mutex::new(receiver),
+        _task: task,
+    });
+}
+
+fn tile_loaders(
+    mut commands: Commands,
+    generation: Option<ResMut<MapGeneration>>,
+    tile_info: Res<TileInfo>,
+    settings: Res<MapSettings>,
+    map_assets: Res<MapAssets>,
+    terrain_query: Query<&TileTerrain>,
+    mut next_map_state: ResMut<NextState<MapStates>>,
+    mut next_main_state: ResMut<NextState<MainStates>>,
+) {
+    let Some(mut generation) = generation else {
+        return;
+    };
+
+    let mut drained = false;
+    for _ in 0..MAX_TILES_TO_LOAD_IN_ONE_UPDATE {
+        let descriptor = {
+            let receiver = generation.receiver.lock().unwrap();
+            match receiver.try_recv() {
+                Ok(descriptor) => descriptor,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    drained = true;
+                    break;
+                }
+            }
+        };
+
+        // Newly spawned tiles start hidden; `update_visibility` lights up
+        // whatever falls in the player's FOV on the very next pass.
+        let Some(mut tile_bundle) = tile_info.create_bundle(
+            &descriptor.tile_type,
+            descriptor.position,
+            TilemapId(generation.tilemap_id),
+            false,
+        ) else {
+            // Untrusted tile data (e.g. a hand-authored Tiled map) can
+            // name a tile type the loaded palette doesn't define; skip
+            // it rather than crash the whole map load.
+            eprintln!(
+                "Unknown tile type {:?} at {:?}, skipping",
+                descriptor.tile_type, descriptor.position
+            );
+            continue;
+        };
+
+        if let (TileTerrain::Diggable { hardness, .. }, Some(override_hardness)) =
+            (&mut tile_bundle.tile_terrain, descriptor.hardness_override)
+        {
+            *hardness = override_hardness;
         }
+
+        let tile_entity = commands.spawn(tile_bundle).id();
+        generation.storage.set(&descriptor.position, tile_entity);
+    }
+
+    if !drained {
+        return;
     }
 
     let tile_size = TilemapTileSize {
@@ -353,18 +741,34 @@ fn create_map(
     };
     let grid_size = tile_size.into();
     let map_type = TilemapType::Square;
+    let map_size = TilemapSize {
+        x: settings.size.0,
+        y: settings.size.1,
+    };
+    let storage = std::mem::replace(&mut generation.storage, TileStorage::empty(map_size));
+    let tilemap_id = generation.tilemap_id;
+
+    let tiles = (0..map_size.x)
+        .flat_map(|x| (0..map_size.y).map(move |y| TilePos { x, y }))
+        .filter_map(|pos| {
+            let entity = storage.get(&pos)?;
+            let terrain = terrain_query.get(entity).ok()?;
+            Some((pos, terrain))
+        });
+    commands.insert_resource(SpatialIndex::new(map_size, tiles));
 
     commands.entity(tilemap_id).insert(TilemapBundle {
         grid_size,
         map_type,
         size: map_size,
-        storage: tile_storage,
+        storage,
         texture: TilemapTexture::Single(map_assets.texture.clone()),
         tile_size,
         transform: Transform::from_xyz(0.0, 0.0, -1.0),
         ..default()
     });
 
+    commands.remove_resource::<MapGeneration>();
     next_map_state.set(MapStates::Generated);
     next_main_state.set(MainStates::InGame);
 }
@@ -374,6 +778,8 @@ impl Plugin for MapPlugin {
         app.add_plugins(TilemapPlugin)
             .add_state::<MapStates>()
             .add_plugins(RonAssetPlugin::<TileInfo>::new(&["info.ron"]))
+            .init_asset::<TiledMap>()
+            .init_asset_loader::<TiledMapLoader>()
             .add_systems(Startup, load_map_assets)
             .add_systems(
                 Update,
@@ -383,7 +789,8 @@ impl Plugin for MapPlugin {
                 Update,
                 update_tile_digging.run_if(in_state(MainStates::InGame)),
             )
-            .add_systems(OnEnter(MapStates::Ready), create_map)
+            .add_systems(OnEnter(MapStates::Ready), start_map_generation)
+            .add_systems(Update, tile_loaders.run_if(in_state(MapStates::Ready)))
             .add_systems(
                 PostUpdate,
                 (update_visibility, update_directional_tiles).run_if(in_state(MainStates::InGame)),
@@ -391,6 +798,10 @@ impl Plugin for MapPlugin {
             .insert_resource(MapSettings {
                 size: (31, 31),
                 tile_size: 16.0,
-            });
+                source: MapSource::Procedural {
+                    seed: seed_from_str("santas-little-digger"),
+                },
+            })
+            .insert_resource(MapSpawnPoint(TilePos::new(31 / 2, 31 / 2)));
     }
 }
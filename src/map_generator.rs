@@ -0,0 +1,170 @@
+//! Cellular-automata cave generation, kept free of any ECS/Bevy types so
+//! it can run on a background task and be unit-tested in isolation.
+
+use std::hash::{Hash, Hasher};
+
+/// The rough shape of a generated cell, before it's mapped onto a
+/// concrete `TileTerrain` back in the `map` module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellClass {
+    Floor,
+    Wall,
+    Border,
+}
+
+/// Derives a deterministic `u64` seed from a human-shareable level code,
+/// so players can exchange maps as plain strings.
+pub fn seed_from_str(code: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    code.hash(&mut hasher);
+    hasher.finish()
+}
+
+const WALL_FILL_DENSITY: f64 = 0.45;
+const SMOOTHING_PASSES: u32 = 5;
+const WALL_NEIGHBOR_THRESHOLD: usize = 5;
+
+/// Minimal splitmix64 PRNG, just enough to turn a `u64` seed into a
+/// reproducible stream of floats without pulling in a `rand` dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_unit_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+/// Generates a cave layout for a `width`x`height` grid from a `u64` seed:
+/// random-fills a wall mask, smooths it into caverns, then guarantees the
+/// spawn area and overall connectivity. The same seed always yields the
+/// same layout.
+pub struct MapGenerator {
+    seed: u64,
+}
+
+impl MapGenerator {
+    pub fn new(seed: u64) -> Self {
+        MapGenerator { seed }
+    }
+
+    pub fn generate(&self, width: u32, height: u32) -> Vec<Vec<CellClass>> {
+        let (w, h) = (width as usize, height as usize);
+        let mut rng = SplitMix64::new(self.seed);
+
+        let mut walls = vec![vec![false; h]; w];
+        for row in walls.iter_mut() {
+            for cell in row.iter_mut() {
+                *cell = rng.next_unit_f64() < WALL_FILL_DENSITY;
+            }
+        }
+
+        for _ in 0..SMOOTHING_PASSES {
+            walls = Self::smooth(&walls, w, h);
+        }
+
+        let center = (width / 2, height / 2);
+        Self::carve_spawn(&mut walls, center, w, h);
+        Self::fill_unreachable_pockets(&mut walls, center, w, h);
+
+        let mut cells = vec![vec![CellClass::Floor; h]; w];
+        for x in 0..w {
+            for y in 0..h {
+                cells[x][y] = if x == 0 || y == 0 || x == w - 1 || y == h - 1 {
+                    CellClass::Border
+                } else if walls[x][y] {
+                    CellClass::Wall
+                } else {
+                    CellClass::Floor
+                };
+            }
+        }
+        cells
+    }
+
+    fn smooth(walls: &[Vec<bool>], w: usize, h: usize) -> Vec<Vec<bool>> {
+        let mut next = vec![vec![false; h]; w];
+        for x in 0..w {
+            for y in 0..h {
+                next[x][y] = Self::count_wall_neighbors(walls, x, y, w, h) >= WALL_NEIGHBOR_THRESHOLD;
+            }
+        }
+        next
+    }
+
+    fn count_wall_neighbors(walls: &[Vec<bool>], x: usize, y: usize, w: usize, h: usize) -> usize {
+        let mut count = 0;
+        for dx in -1i32..=1 {
+            for dy in -1i32..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx < 0 || ny < 0 || nx as usize >= w || ny as usize >= h {
+                    // Treat the area outside the map as solid rock.
+                    count += 1;
+                } else if walls[nx as usize][ny as usize] {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn carve_spawn(walls: &mut [Vec<bool>], center: (u32, u32), w: usize, h: usize) {
+        let (cx, cy) = (center.0 as i32, center.1 as i32);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (x, y) = (cx + dx, cy + dy);
+                if x >= 0 && y >= 0 && (x as usize) < w && (y as usize) < h {
+                    walls[x as usize][y as usize] = false;
+                }
+            }
+        }
+    }
+
+    /// Flood-fills from the spawn tile and turns any floor cell it can't
+    /// reach back into a wall, so the walkable area is one connected blob.
+    /// The outer ring is never entered, since it becomes `Border` later.
+    fn fill_unreachable_pockets(walls: &mut [Vec<bool>], center: (u32, u32), w: usize, h: usize) {
+        let (cx, cy) = (center.0 as usize, center.1 as usize);
+        let mut reachable = vec![vec![false; h]; w];
+        let mut stack = vec![(cx, cy)];
+        reachable[cx][cy] = true;
+
+        while let Some((x, y)) = stack.pop() {
+            for (dx, dy) in [(-1i32, 0i32), (1, 0), (0, -1), (0, 1)] {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if nx <= 0 || ny <= 0 || nx as usize >= w - 1 || ny as usize >= h - 1 {
+                    continue;
+                }
+
+                let (nx, ny) = (nx as usize, ny as usize);
+                if !walls[nx][ny] && !reachable[nx][ny] {
+                    reachable[nx][ny] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+
+        for x in 0..w {
+            for y in 0..h {
+                if !walls[x][y] && !reachable[x][y] {
+                    walls[x][y] = true;
+                }
+            }
+        }
+    }
+}
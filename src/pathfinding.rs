@@ -0,0 +1,114 @@
+//! A* pathfinding over the tile grid, independent of any ECS state so it
+//! can be driven by whatever cost function the caller's terrain lookup
+//! produces.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use bevy_ecs_tilemap::prelude::{TilePos, TilemapSize};
+
+#[derive(Copy, Clone, PartialEq)]
+struct ScoredPos {
+    position: TilePos,
+    estimated_total_cost: f32,
+}
+
+impl Eq for ScoredPos {}
+
+impl Ord for ScoredPos {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so a `BinaryHeap` (normally a max-heap) pops the
+        // lowest estimated cost first.
+        other
+            .estimated_total_cost
+            .partial_cmp(&self.estimated_total_cost)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for ScoredPos {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Manhattan distance scaled by the cheapest possible move cost, so the
+/// heuristic never overestimates the true remaining cost.
+fn heuristic(from: TilePos, to: TilePos, min_move_cost: f32) -> f32 {
+    let dx = (from.x as i32 - to.x as i32).unsigned_abs();
+    let dy = (from.y as i32 - to.y as i32).unsigned_abs();
+    (dx + dy) as f32 * min_move_cost
+}
+
+fn neighbors(pos: TilePos, map_size: &TilemapSize) -> impl Iterator<Item = TilePos> + '_ {
+    let (x, y) = (pos.x as i32, pos.y as i32);
+    [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+        .into_iter()
+        .filter_map(|(nx, ny)| TilePos::from_i32_pair(nx, ny, map_size))
+}
+
+/// Finds the cheapest route from `start` to `goal` with A*. `move_cost`
+/// gives the cost of entering a tile (`None` treats it as impassable);
+/// `min_move_cost` must be a lower bound on every cost `move_cost` can
+/// return, so the heuristic stays admissible. Returns the path excluding
+/// `start`, in travel order, or `None` if `goal` is unreachable.
+pub fn find_path(
+    start: TilePos,
+    goal: TilePos,
+    map_size: &TilemapSize,
+    min_move_cost: f32,
+    move_cost: impl Fn(TilePos) -> Option<f32>,
+) -> Option<Vec<TilePos>> {
+    if start == goal {
+        return Some(Vec::new());
+    }
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<TilePos, TilePos> = HashMap::new();
+    let mut best_cost: HashMap<TilePos, f32> = HashMap::new();
+
+    best_cost.insert(start, 0.0);
+    open.push(ScoredPos {
+        position: start,
+        estimated_total_cost: heuristic(start, goal, min_move_cost),
+    });
+
+    while let Some(ScoredPos { position, .. }) = open.pop() {
+        if position == goal {
+            return Some(reconstruct_path(&came_from, goal));
+        }
+
+        let current_cost = best_cost.get(&position).copied().unwrap_or(f32::INFINITY);
+
+        for neighbor in neighbors(position, map_size) {
+            let Some(step_cost) = move_cost(neighbor) else {
+                continue;
+            };
+
+            let tentative_cost = current_cost + step_cost;
+            if tentative_cost < best_cost.get(&neighbor).copied().unwrap_or(f32::INFINITY) {
+                came_from.insert(neighbor, position);
+                best_cost.insert(neighbor, tentative_cost);
+                open.push(ScoredPos {
+                    position: neighbor,
+                    estimated_total_cost: tentative_cost + heuristic(neighbor, goal, min_move_cost),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+fn reconstruct_path(came_from: &HashMap<TilePos, TilePos>, goal: TilePos) -> Vec<TilePos> {
+    // `start` never gets a `came_from` entry, so the walk stops before
+    // pushing it.
+    let mut path = Vec::new();
+    let mut current = goal;
+    while let Some(&prev) = came_from.get(&current) {
+        path.push(current);
+        current = prev;
+    }
+    path.reverse();
+    path
+}
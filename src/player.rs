@@ -1,5 +1,9 @@
-use crate::map::{MapSettings, TileDigging, TileType};
+use std::collections::VecDeque;
+
+use crate::map::{MapSettings, MapSpawnPoint, TileDigging, TileTerrain};
+use crate::pathfinding;
 use crate::prelude::*;
+use crate::spatial::SpatialIndex;
 
 pub struct PlayerPlugin;
 
@@ -9,7 +13,12 @@ pub struct Player;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(MainStates::InGame), spawn_player)
-            .add_systems(Update, move_player.run_if(in_state(MainStates::InGame)));
+            .add_systems(
+                Update,
+                (move_player, handle_click_to_move, advance_path_follow)
+                    .chain()
+                    .run_if(in_state(MainStates::InGame)),
+            );
     }
 }
 
@@ -18,17 +27,16 @@ pub struct PlayerBundle {
     player: Player,
     sprite_bundle: SpriteBundle,
     position: GridPosition,
+    fov_source: FovSource,
 }
 
 fn spawn_player(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     map_settings: Res<MapSettings>,
+    spawn_point: Res<MapSpawnPoint>,
 ) {
-    let position = GridPosition(TilePos::new(
-        map_settings.size.0 / 2,
-        map_settings.size.1 / 2,
-    ));
+    let position = GridPosition(spawn_point.0);
 
     let sprite = SpriteBundle {
         texture: asset_server.load("digger.png"),
@@ -48,11 +56,36 @@ fn spawn_player(
     });
 }
 
+/// Base time, in seconds, to walk into an already-clear tile. Digging a
+/// `Diggable` tile scales this by `1.0 + hardness`.
+const BASE_MOVE_TIME: f32 = 0.5;
+
+/// Time to enter `terrain`, or `None` if it can't be entered at all.
+fn move_cost(terrain: &TileTerrain) -> Option<f32> {
+    match terrain {
+        TileTerrain::Walkable => Some(BASE_MOVE_TIME),
+        TileTerrain::Diggable { hardness, .. } => Some(BASE_MOVE_TIME * (1.0 + hardness)),
+        TileTerrain::Impassable => None,
+    }
+}
+
+/// A queued route for an entity to auto-walk one tile at a time, dug out
+/// by `advance_path_follow` as it goes.
+#[derive(Component)]
+pub struct PathFollow(VecDeque<TilePos>);
+
+impl PathFollow {
+    fn new(path: Vec<TilePos>) -> Self {
+        PathFollow(path.into())
+    }
+}
+
 fn move_player(
     mut commands: Commands,
     player_query: Query<(Entity, &GridPosition), Without<MoveTo>>,
     map_query: Query<(&TilemapSize, &TileStorage)>,
-    tile_query: Query<&TileType>,
+    tile_query: Query<&TileTerrain>,
+    spatial_index: Res<SpatialIndex>,
     input: Res<Input<KeyCode>>,
 ) {
     let (map_size, tiles) = map_query.single();
@@ -77,23 +110,155 @@ fn move_player(
             );
 
             if let Some(new_pos) = new_pos {
-                let tile_entity = tiles.get(&new_pos).expect("Tile entity should exist!");
-
-                let move_speed = match tile_query
-                    .get(tile_entity)
-                    .expect("Tile should have a tile type")
+                // Another entity already standing there blocks the move,
+                // whatever the terrain underneath.
+                if spatial_index
+                    .occupants(new_pos)
+                    .iter()
+                    .any(|&occupant| occupant != e)
                 {
-                    TileType::Walkable => 0.5,
-                    TileType::Diggable { hardness, .. } => {
-                        let time = 0.5 * (1.0 + hardness);
-                        commands.entity(tile_entity).insert(TileDigging::new(time));
-                        time
-                    }
-                    TileType::Impassable => return,
+                    return;
+                }
+
+                let Some(tile_entity) = tiles.get(&new_pos) else {
+                    return;
+                };
+                let terrain = tile_query.get(tile_entity).expect("Tile should have a tile type");
+
+                let Some(time) = move_cost(terrain) else {
+                    return;
                 };
+                if let TileTerrain::Diggable { .. } = terrain {
+                    commands.entity(tile_entity).insert(TileDigging::new(time));
+                }
 
-                commands.entity(e).insert(MoveTo::new(new_pos, move_speed));
+                // Manual input overrides whatever auto-walk was in progress.
+                commands
+                    .entity(e)
+                    .remove::<PathFollow>()
+                    .insert(MoveTo::new(new_pos, time));
             }
         }
     }
 }
+
+fn handle_click_to_move(
+    mut commands: Commands,
+    mouse_button: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    map_query: Query<(&TilemapSize, &TilemapGridSize, &TilemapType, &TileStorage)>,
+    tile_query: Query<&TileTerrain>,
+    player_query: Query<(Entity, &GridPosition), With<Player>>,
+    spatial_index: Res<SpatialIndex>,
+) {
+    if !mouse_button.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Some(cursor_pos) = window.cursor_position() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Some(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor_pos) else {
+        return;
+    };
+    let Ok((map_size, grid_size, map_type, tile_storage)) = map_query.get_single() else {
+        return;
+    };
+    let Some(target) = TilePos::from_world_pos(&world_pos, map_size, grid_size, map_type) else {
+        return;
+    };
+    let Ok((entity, grid_pos)) = player_query.get_single() else {
+        return;
+    };
+
+    let cost_at = |pos: TilePos| -> Option<f32> {
+        // Another entity occupying `pos` rules it out regardless of
+        // terrain; otherwise consult the spatial index before falling
+        // back to `TileStorage` for the uncommon non-walkable case.
+        if spatial_index
+            .occupants(pos)
+            .iter()
+            .any(|&occupant| occupant != entity)
+        {
+            return None;
+        }
+        if !spatial_index.is_blocked(pos) {
+            return Some(BASE_MOVE_TIME);
+        }
+
+        let tile_entity = tile_storage.get(&pos)?;
+        move_cost(tile_query.get(tile_entity).ok()?)
+    };
+
+    let Some(path) = pathfinding::find_path(**grid_pos, target, map_size, BASE_MOVE_TIME, cost_at) else {
+        return;
+    };
+    if path.is_empty() {
+        return;
+    }
+
+    commands.entity(entity).insert(PathFollow::new(path));
+}
+
+fn advance_path_follow(
+    mut commands: Commands,
+    mut follower_query: Query<(Entity, &mut PathFollow), Without<MoveTo>>,
+    tile_storage: Query<&TileStorage>,
+    tile_query: Query<&TileTerrain>,
+    spatial_index: Res<SpatialIndex>,
+) {
+    let Ok(tile_storage) = tile_storage.get_single() else {
+        return;
+    };
+
+    for (entity, mut path_follow) in follower_query.iter_mut() {
+        let Some(next_pos) = path_follow.0.pop_front() else {
+            commands.entity(entity).remove::<PathFollow>();
+            continue;
+        };
+
+        if spatial_index
+            .occupants(next_pos)
+            .iter()
+            .any(|&occupant| occupant != entity)
+        {
+            // Something else has since moved onto the planned tile;
+            // give up the walk rather than step into it.
+            commands.entity(entity).remove::<PathFollow>();
+            continue;
+        }
+
+        let terrain = tile_storage
+            .get(&next_pos)
+            .and_then(|tile_entity| tile_query.get(tile_entity).ok().map(|t| (tile_entity, t)));
+
+        let Some((tile_entity, terrain)) = terrain else {
+            // Tile vanished under the planned route; give up the walk.
+            commands.entity(entity).remove::<PathFollow>();
+            continue;
+        };
+
+        let Some(time) = move_cost(terrain) else {
+            // Terrain changed since the path was planned; stop here
+            // rather than walking into what is now a wall.
+            commands.entity(entity).remove::<PathFollow>();
+            continue;
+        };
+        if let TileTerrain::Diggable { .. } = terrain {
+            commands.entity(tile_entity).insert(TileDigging::new(time));
+        }
+
+        commands.entity(entity).insert(MoveTo::new(next_pos, time));
+
+        if path_follow.0.is_empty() {
+            commands.entity(entity).remove::<PathFollow>();
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::map::TileTerrain;
+use crate::prelude::*;
+
+pub struct SpatialPlugin;
+
+impl Plugin for SpatialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (update_blocked, update_occupants).run_if(resource_exists::<SpatialIndex>()),
+        );
+    }
+}
+
+/// Per-tile occupancy and walkability, kept in sync with the ECS so
+/// movement and pathfinding can query it in O(1) without touching
+/// `TileStorage` or walking entities themselves.
+#[derive(Resource)]
+pub struct SpatialIndex {
+    map_size: TilemapSize,
+    blocked: Vec<bool>,
+    occupants: Vec<Vec<Entity>>,
+}
+
+impl SpatialIndex {
+    /// Builds an index for `map_size`, seeded with the given tiles'
+    /// initial terrain so callers don't have to wait a frame for
+    /// `update_blocked` to pick up what was just spawned.
+    pub fn new<'a>(
+        map_size: TilemapSize,
+        tiles: impl IntoIterator<Item = (TilePos, &'a TileTerrain)>,
+    ) -> Self {
+        let len = (map_size.x * map_size.y) as usize;
+        let mut index = SpatialIndex {
+            map_size,
+            blocked: vec![true; len],
+            occupants: vec![Vec::new(); len],
+        };
+
+        for (pos, terrain) in tiles {
+            index.set_blocked(pos, is_blocking_terrain(terrain));
+        }
+
+        index
+    }
+
+    pub fn to_index(&self, pos: TilePos) -> usize {
+        (pos.y * self.map_size.x + pos.x) as usize
+    }
+
+    pub fn is_blocked(&self, pos: TilePos) -> bool {
+        self.blocked
+            .get(self.to_index(pos))
+            .copied()
+            .unwrap_or(true)
+    }
+
+    pub fn occupants(&self, pos: TilePos) -> &[Entity] {
+        self.occupants
+            .get(self.to_index(pos))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn set_blocked(&mut self, pos: TilePos, blocked: bool) {
+        let index = self.to_index(pos);
+        self.blocked[index] = blocked;
+    }
+}
+
+fn is_blocking_terrain(terrain: &TileTerrain) -> bool {
+    !matches!(terrain, TileTerrain::Walkable)
+}
+
+fn update_blocked(
+    changed_terrain: Query<(&TilePos, &TileTerrain), Changed<TileTerrain>>,
+    mut spatial_index: ResMut<SpatialIndex>,
+) {
+    for (tile_pos, terrain) in changed_terrain.iter() {
+        spatial_index.set_blocked(*tile_pos, is_blocking_terrain(terrain));
+    }
+}
+
+fn update_occupants(
+    occupant_query: Query<(Entity, &GridPosition)>,
+    mut spatial_index: ResMut<SpatialIndex>,
+) {
+    for slot in spatial_index.occupants.iter_mut() {
+        slot.clear();
+    }
+
+    for (entity, grid_pos) in occupant_query.iter() {
+        let index = spatial_index.to_index(**grid_pos);
+        spatial_index.occupants[index].push(entity);
+    }
+}
@@ -0,0 +1,133 @@
+//! Loads Tiled (`.tmx`) maps as an alternative to procedural generation.
+//!
+//! This only extracts what the rest of the game needs: a named tile per
+//! grid cell (looked up via each Tiled tileset tile's custom `ID`
+//! property against the same names `TileInfo` knows) and a spawn point.
+//! Parsing happens entirely in the loader, against an embedded tileset,
+//! so no extra filesystem access is needed once the `.tmx` bytes are in
+//! hand.
+
+use std::io::Cursor;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::reflect::TypePath;
+use bevy::utils::BoxedFuture;
+use bevy_ecs_tilemap::prelude::TilePos;
+
+/// A Tiled map, reduced to the tile names and spawn point the map
+/// generator pipeline needs.
+#[derive(bevy::asset::Asset, TypePath, Debug, Clone)]
+pub struct TiledMap {
+    pub width: u32,
+    pub height: u32,
+    /// One entry per occupied cell in the map's tile layer, naming the
+    /// matching `TileInfo` entry.
+    pub tiles: Vec<(TilePos, String)>,
+    pub spawn: TilePos,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TiledMapLoadError {
+    #[error("could not read Tiled map bytes: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse Tiled map: {0}")]
+    Parse(#[from] tiled::Error),
+    #[error("Tiled map has no tile layer")]
+    MissingTileLayer,
+}
+
+#[derive(Default)]
+pub struct TiledMapLoader;
+
+impl AssetLoader for TiledMapLoader {
+    type Asset = TiledMap;
+    type Settings = ();
+    type Error = TiledMapLoadError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<TiledMap, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+
+            let map = tiled::Loader::new()
+                .load_tmx_map_from(Cursor::new(bytes), load_context.path())?;
+
+            to_tiled_map(&map)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmx"]
+    }
+}
+
+/// The property on a tileset tile naming the `TileInfo` entry it maps
+/// to, e.g. `ID = "ground"`.
+const ID_PROPERTY: &str = "ID";
+
+/// The name of the object (on any object layer) marking where the
+/// player should spawn.
+const SPAWN_OBJECT_NAME: &str = "spawn";
+
+fn to_tiled_map(map: &tiled::Map) -> Result<TiledMap, TiledMapLoadError> {
+    let width = map.width;
+    let height = map.height;
+
+    let tile_layer = map
+        .layers()
+        .find_map(|layer| layer.as_tile_layer())
+        .ok_or(TiledMapLoadError::MissingTileLayer)?;
+
+    let mut tiles = Vec::new();
+    for x in 0..width {
+        for y in 0..height {
+            let Some(layer_tile) = tile_layer.get_tile(x as i32, y as i32) else {
+                continue;
+            };
+            let Some(tile_type) = layer_tile
+                .get_tile()
+                .and_then(|tile| tile.properties.get(ID_PROPERTY))
+                .and_then(|value| match value {
+                    tiled::PropertyValue::StringValue(s) => Some(s.clone()),
+                    _ => None,
+                })
+            else {
+                continue;
+            };
+
+            // Tiled's Y axis points down; our grid's points up.
+            let position = TilePos {
+                x,
+                y: height - 1 - y,
+            };
+            tiles.push((position, tile_type));
+        }
+    }
+
+    let spawn = map
+        .layers()
+        .filter_map(|layer| layer.as_object_layer())
+        .flat_map(|layer| layer.objects())
+        .find(|object| object.name.eq_ignore_ascii_case(SPAWN_OBJECT_NAME))
+        .map(|object| TilePos {
+            x: (object.x / map.tile_width as f32) as u32,
+            y: height - 1 - (object.y / map.tile_height as f32) as u32,
+        })
+        .unwrap_or(TilePos {
+            x: width / 2,
+            y: height / 2,
+        });
+
+    Ok(TiledMap {
+        width,
+        height,
+        tiles,
+        spawn,
+    })
+}